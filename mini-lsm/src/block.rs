@@ -10,26 +10,29 @@ pub const SIZEOF_U16: usize = std::mem::size_of::<u16>();
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
 /*
-block struct: 
-|          data         |           offsets         |
+block struct:
+|          data         |          restarts         |
 |entry|entry|entry|entry|offset|offset|offset|offset|num_of_elements|
 
-offsets and num: 
+restarts and num:
 |offset|offset|num_of_elements|
 |   0  |  12  |       2       |
 
 ----------------------------------------------------------
-entry struct:
-Key length and value length are 2 Bytes, which means their maximum length is 65536.
+entry struct (prefix-compressed, LevelDB-style restart points):
+shared_len/non_shared_len/value_len are 2 Bytes, which means their maximum length is 65535.
 
-|                             entry1                            |
-| key_len (2B) | key  | value_len (2B) | value  | ... |
+Every `restart_interval`-th entry is a "restart": shared_len is always 0 and the full key
+is stored, so seeking can binary-search the restarts array before scanning forward.
+
+|                                     entry1                                    |
+| shared_len (2B) | non_shared_len (2B) | value_len (2B) | non_shared_key | value | ... |
 
 */
 #[derive(Debug)]
 pub struct Block{
     data: Vec<u8>,
-    offsets: Vec<u16>,
+    restarts: Vec<u16>,
 }
 
 
@@ -37,33 +40,33 @@ impl Block {
 
     pub fn encode(&self) -> Bytes {
         let mut buf = self.data.clone();
-        let offset_len = self.offsets.len();
+        let restarts_len = self.restarts.len();
 
-        for offset in &self.offsets {
-            buf.put_u16(*offset);
+        for restart in &self.restarts {
+            buf.put_u16(*restart);
         }
         // num_of_elements at the end of the block store as u16
-        buf.put_u16(offset_len as u16);
+        buf.put_u16(restarts_len as u16);
         buf.into()
     }
 
     pub fn decode(data: &[u8]) -> Self {
         // should be num_of_elements
-        let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
-        // println!("entry_offsets_len: {}", entry_offsets_len);
+        let num_restarts = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
+        // println!("num_restarts: {}", num_restarts);
 
-        let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
-        let offsets_raw = &data[data_end..data.len()-SIZEOF_U16];
+        let data_end = data.len() - SIZEOF_U16 - num_restarts * SIZEOF_U16;
+        let restarts_raw = &data[data_end..data.len()-SIZEOF_U16];
 
-        let offsets = offsets_raw
+        let restarts = restarts_raw
             .chunks(SIZEOF_U16)
             .map(|mut x| x.get_u16())
             .collect();
 
         let data = data[0..data_end].to_vec();
-        Self { data,  offsets}
+        Self { data,  restarts}
     }
-    
+
 }
 
 #[cfg(test)]
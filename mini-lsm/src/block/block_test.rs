@@ -0,0 +1,80 @@
+use super::*;
+
+fn build_block(entries: &[(&[u8], &[u8])], restart_interval: usize) -> Block {
+    let mut builder = BlockBuilder::new_with_restart_interval(4096, restart_interval);
+    for (key, value) in entries {
+        assert!(builder.add(key, value), "block_size too small for test data");
+    }
+    builder.build()
+}
+
+/// The entry right after a restart is prefix-compressed against that restart entry (not against
+/// the last entry of the *previous* restart group), while the restart entry itself always stores
+/// its key in full. Both must decode back to the original key when walked forward across the
+/// boundary.
+#[test]
+fn prefix_compressed_entries_decode_across_restart_boundary() {
+    let entries: &[(&[u8], &[u8])] = &[
+        (b"key01", b"v1"),
+        (b"key02", b"v2"),
+        (b"key03", b"v3"),
+        (b"key04", b"v4"),
+    ];
+    // restart_interval = 2 puts restarts at entries 0 and 2, so entry 2 ("key03") is itself a
+    // restart (full key, no shared prefix) while entry 3 ("key04") is prefix-compressed against
+    // it, not against entry 1.
+    let block = build_block(entries, 2);
+    assert_eq!(block.restarts.len(), 2);
+
+    let mut iter = BlockIterator::create_and_seek_to_first(std::sync::Arc::new(block));
+    for (key, value) in entries {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), *key);
+        assert_eq!(iter.value(), *value);
+        iter.next();
+    }
+    assert!(!iter.is_valid());
+}
+
+/// `seek_to_key` binary-searches the restart points, then scans forward from whichever restart
+/// precedes the target; a key landing strictly between two restarts (rather than on one) must
+/// still resolve to the first entry `>=` it.
+#[test]
+fn seek_to_key_between_restarts() {
+    let entries: &[(&[u8], &[u8])] = &[
+        (b"key01", b"v1"),
+        (b"key03", b"v3"),
+        (b"key05", b"v5"),
+        (b"key07", b"v7"),
+    ];
+    // restart_interval = 2 -> restarts at "key01" and "key05"; "key04" sits strictly between
+    // them, with no entry of its own.
+    let block = build_block(entries, 2);
+    let block = std::sync::Arc::new(block);
+
+    let mut iter = BlockIterator::create_and_seek_to_key(block.clone(), b"key04");
+    assert!(iter.is_valid());
+    assert_eq!(iter.key(), b"key05");
+    assert_eq!(iter.value(), b"v5");
+
+    // Seeking exactly to a restart key lands on it directly.
+    let mut iter = BlockIterator::create_and_seek_to_key(block.clone(), b"key05");
+    assert!(iter.is_valid());
+    assert_eq!(iter.key(), b"key05");
+
+    // Seeking past every key leaves the iterator exhausted.
+    let iter = BlockIterator::create_and_seek_to_key(block, b"key99");
+    assert!(!iter.is_valid());
+}
+
+/// `Block::decode` trusts its input to be exactly what `Block::encode` produced, with no crc32
+/// of its own to catch corruption at this layer (that's `SsTable::read_block`'s job, over the
+/// whole on-disk record). A buffer claiming far more restarts than it has room for is expected
+/// to panic rather than silently misparse.
+#[test]
+#[should_panic]
+fn decode_corrupted_block_panics() {
+    // num_of_elements (last 2 bytes) claims 0xFFFF restarts, which can't possibly fit in a
+    // 2-byte buffer; computing where the restarts array starts underflows.
+    Block::decode(&[0xFF, 0xFF]);
+}
@@ -1,28 +1,49 @@
 use super::{Block, SIZEOF_U16};
 use bytes::BufMut;
 
+/// Default number of entries between restart points, matching the LevelDB convention.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+/// Length of the shared prefix `key` has in common with `other`.
+fn common_prefix_len(other: &[u8], key: &[u8]) -> usize {
+    other.iter().zip(key).take_while(|(a, b)| a == b).count()
+}
+
 /// Builds a block
 pub struct BlockBuilder {
-    /// Offsets of each key-value entries.
-    offsets: Vec<u16>,
-    /// All key-value pairs in the block.
+    /// Byte offset of each restart point's entry within `data`.
+    restarts: Vec<u16>,
+    /// All key-value pairs in the block, prefix-compressed against restart points.
     data: Vec<u8>,
     ///  The expected block size of byte.
     block_size: usize,
+    /// The last key added, used to compute the shared prefix of the next entry.
+    last_key: Vec<u8>,
+    /// Number of entries added since (and including) the last restart point.
+    since_restart: usize,
+    /// Emit a restart (full key, shared_len=0) every `restart_interval` entries.
+    restart_interval: usize,
 }
 
 impl BlockBuilder {
     pub fn new(size: usize) -> Self {
+        Self::new_with_restart_interval(size, DEFAULT_RESTART_INTERVAL)
+    }
+
+    pub fn new_with_restart_interval(size: usize, restart_interval: usize) -> Self {
         Self {
-            offsets: Vec::new(),
+            restarts: Vec::new(),
             data: Vec::new(),
             block_size: size,
+            last_key: Vec::new(),
+            since_restart: 0,
+            restart_interval,
         }
     }
 
     /// Return the size of a block except num_of_elements
     fn estimated_size(&self) -> usize {
-        let cur_size = self.offsets.len() * SIZEOF_U16 + self.data.len() + SIZEOF_U16;
+        let cur_size = self.restarts.len() * SIZEOF_U16 + self.data.len() + SIZEOF_U16;
         // println!("cur size {}", cur_size);
         cur_size
     }
@@ -31,25 +52,40 @@ impl BlockBuilder {
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
 
-        // cur size + new key val size + (key/val len size + num_of_elements size)
-        if self.estimated_size() + key.len() + value.len() + SIZEOF_U16 * 3 > self.block_size
+        let is_restart = self.since_restart == 0;
+        let shared_len = if is_restart {
+            0
+        } else {
+            common_prefix_len(&self.last_key, key)
+        };
+        let non_shared = &key[shared_len..];
+
+        // cur size + new entry size + (shared/non_shared/value len size + num_of_elements size)
+        if self.estimated_size() + non_shared.len() + value.len() + SIZEOF_U16 * 4 > self.block_size
             && !self.is_empty()
         {
             return false;
         }
-        self.offsets.push(self.data.len() as u16);
 
-        // b"22" 字节字符串, 占两个字节，put_u16 两个字节
-        self.data.put_u16(key.len() as u16);
-        self.data.put(key);
+        if is_restart {
+            self.restarts.push(self.data.len() as u16);
+        }
+
+        self.data.put_u16(shared_len as u16);
+        self.data.put_u16(non_shared.len() as u16);
         self.data.put_u16(value.len() as u16);
+        self.data.put(non_shared);
         self.data.put(value);
 
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.since_restart = (self.since_restart + 1) % self.restart_interval;
+
         true
     }
 
     pub fn is_empty(&self) -> bool {
-        self.offsets.is_empty()
+        self.restarts.is_empty()
     }
 
     // Finalize the block.
@@ -60,7 +96,7 @@ impl BlockBuilder {
 
         Block {
             data: self.data,
-            offsets: self.offsets,
+            restarts: self.restarts,
         }
     }
 }
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use bytes::Buf;
+
+use super::{Block, SIZEOF_U16};
+
+/// Iterates over the key-value pairs in a single `Block`, reconstructing each key from the
+/// restart-point prefix compression described in `block.rs`.
+pub struct BlockIterator {
+    block: Arc<Block>,
+    /// The current key, rebuilt by truncating to `shared_len` and appending the non-shared bytes.
+    key: Vec<u8>,
+    /// Byte range of the current value within `block.data`.
+    value_range: (usize, usize),
+    /// Byte offset of the current entry within `block.data`.
+    offset: usize,
+    /// Byte offset of the entry following the current one, cached from the last decode.
+    next_offset: usize,
+}
+
+impl BlockIterator {
+    fn new(block: Arc<Block>) -> Self {
+        Self {
+            block,
+            key: Vec::new(),
+            value_range: (0, 0),
+            offset: 0,
+            next_offset: 0,
+        }
+    }
+
+    pub fn create_and_seek_to_first(block: Arc<Block>) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to_first();
+        iter
+    }
+
+    pub fn create_and_seek_to_key(block: Arc<Block>, key: &[u8]) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to_key(key);
+        iter
+    }
+
+    /// The current key, or empty if the iterator is exhausted.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// The current value, or empty if the iterator is exhausted.
+    pub fn value(&self) -> &[u8] {
+        &self.block.data[self.value_range.0..self.value_range.1]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.key.is_empty()
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.seek_to_offset(0);
+    }
+
+    pub fn next(&mut self) {
+        self.seek_to_offset(self.next_offset);
+    }
+
+    /// Binary search the restart points for the entry at or before `key`, then scan forward
+    /// entry-by-entry (shared-prefix decoding only works moving forward from a restart).
+    pub fn seek_to_key(&mut self, key: &[u8]) {
+        if self.block.restarts.is_empty() {
+            self.seek_to_offset(0);
+            return;
+        }
+
+        let mut lo = 0usize;
+        let mut hi = self.block.restarts.len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            self.seek_to_offset(self.block.restarts[mid] as usize);
+            if self.key.as_slice() <= key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let restart_idx = lo.saturating_sub(1);
+        self.seek_to_offset(self.block.restarts[restart_idx] as usize);
+        while self.is_valid() && self.key.as_slice() < key {
+            self.next();
+        }
+    }
+
+    /// Decode the entry at `offset`, leaving `key`/`value_range` pointing at it and caching
+    /// `next_offset`. `offset == block.data.len()` marks an exhausted iterator.
+    fn seek_to_offset(&mut self, offset: usize) {
+        self.offset = offset;
+
+        if offset >= self.block.data.len() {
+            self.key.clear();
+            self.value_range = (0, 0);
+            self.next_offset = offset;
+            return;
+        }
+
+        let mut rest = &self.block.data[offset..];
+        let shared_len = rest.get_u16() as usize;
+        let non_shared_len = rest.get_u16() as usize;
+        let value_len = rest.get_u16() as usize;
+
+        let non_shared_start = offset + SIZEOF_U16 * 3;
+        let non_shared = &self.block.data[non_shared_start..non_shared_start + non_shared_len];
+
+        self.key.truncate(shared_len);
+        self.key.extend_from_slice(non_shared);
+
+        let value_start = non_shared_start + non_shared_len;
+        self.value_range = (value_start, value_start + value_len);
+        self.next_offset = value_start + value_len;
+    }
+}
@@ -0,0 +1,18 @@
+pub mod merge_iterator;
+pub mod two_merge_iterator;
+
+use anyhow::Result;
+
+/// Common interface every iterator in the read path implements: a cursor over `(key, value)`
+/// pairs in ascending key order, where `key` is an MVCC-encoded key (`user_key + !ts`, see
+/// `crate::table::encode_key_with_ts`).
+pub trait StorageIterator {
+    /// The current entry's key. Only valid to call while `is_valid()`.
+    fn key(&self) -> &[u8];
+    /// The current entry's value. Only valid to call while `is_valid()`.
+    fn value(&self) -> &[u8];
+    /// Whether the iterator currently points at an entry.
+    fn is_valid(&self) -> bool;
+    /// Advance to the next entry.
+    fn next(&mut self) -> Result<()>;
+}
@@ -0,0 +1,114 @@
+use std::cmp::Ordering;
+use std::collections::binary_heap::PeekMut;
+use std::collections::BinaryHeap;
+
+use anyhow::Result;
+
+use super::StorageIterator;
+
+struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
+
+impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<I: StorageIterator> Eq for HeapWrapper<I> {}
+
+impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: StorageIterator> Ord for HeapWrapper<I> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap; we want the smallest key (and, on a tie, the
+        // lowest/highest-priority index) to pop first, so compare in reverse.
+        self.1
+            .key()
+            .cmp(other.1.key())
+            .then(self.0.cmp(&other.0))
+            .reverse()
+    }
+}
+
+/// Merges several iterators of the same concrete type into one, reading in ascending key order.
+/// When multiple iterators hold the same key, the one earliest in the `iters` passed to
+/// `create` wins (its value is surfaced; the others are silently advanced past it) — callers
+/// rely on this to put the most up-to-date source first (see every `MergeIterator::create` call
+/// site).
+pub struct MergeIterator<I: StorageIterator> {
+    iters: BinaryHeap<HeapWrapper<I>>,
+    current: Option<HeapWrapper<I>>,
+}
+
+impl<I: StorageIterator> MergeIterator<I> {
+    pub fn create(iters: Vec<Box<I>>) -> Self {
+        let mut heap = BinaryHeap::new();
+        for (idx, iter) in iters.into_iter().enumerate() {
+            if iter.is_valid() {
+                heap.push(HeapWrapper(idx, iter));
+            }
+        }
+        let current = heap.pop();
+        Self {
+            iters: heap,
+            current,
+        }
+    }
+}
+
+impl<I: StorageIterator> StorageIterator for MergeIterator<I> {
+    fn key(&self) -> &[u8] {
+        self.current.as_ref().unwrap().1.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.current.as_ref().unwrap().1.value()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.current.as_ref().is_some_and(|c| c.1.is_valid())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        let current = self.current.as_mut().unwrap();
+
+        // Advance every other iterator sitting on the same key so its stale version isn't
+        // surfaced once `current` moves off this key.
+        while let Some(mut inner) = self.iters.peek_mut() {
+            if inner.1.key() != current.1.key() {
+                break;
+            }
+            if let Err(e) = inner.1.next() {
+                PeekMut::pop(inner);
+                return Err(e);
+            }
+            if !inner.1.is_valid() {
+                PeekMut::pop(inner);
+            }
+        }
+
+        current.1.next()?;
+
+        if !current.1.is_valid() {
+            if let Some(next) = self.iters.pop() {
+                *current = next;
+            }
+            return Ok(());
+        }
+
+        if let Some(next) = self.iters.peek() {
+            if *next > *current {
+                if let Some(next) = self.iters.pop() {
+                    let old_current = std::mem::replace(current, next);
+                    self.iters.push(old_current);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
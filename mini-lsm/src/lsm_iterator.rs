@@ -0,0 +1,162 @@
+use std::ops::Bound;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::iterators::StorageIterator;
+use crate::mem_table::MemtableIterator;
+use crate::table::{split_key_ts, user_key, SsTableIterator};
+
+type LsmIteratorInner =
+    TwoMergeIterator<MergeIterator<MemtableIterator>, MergeIterator<SsTableIterator>>;
+
+/// The top-level read-path iterator `LsmStorage::scan` returns: merges the memtable and on-disk
+/// sides (via `LsmIteratorInner`), then dedupes by user key and filters by `read_ts`, yielding at
+/// most one (non-tombstone) entry per user key, in ascending user-key order, bounded by
+/// `end_bound`.
+pub struct LsmIterator {
+    inner: LsmIteratorInner,
+    end_bound: Bound<Bytes>,
+    read_ts: u64,
+    is_valid: bool,
+    /// The last user key this iterator has already resolved (surfaced, or found deleted by a
+    /// tombstone visible at `read_ts`); every further version of it is skipped without
+    /// re-inspecting its ts.
+    prev_key: Option<Vec<u8>>,
+}
+
+impl LsmIterator {
+    /// Wrap `iter`, visiting only entries visible as of `read_ts` up to (but not including, for
+    /// `Bound::Excluded`) `end_bound`.
+    pub fn new_with_read_ts(
+        iter: LsmIteratorInner,
+        end_bound: Bound<Bytes>,
+        read_ts: u64,
+    ) -> Result<Self> {
+        let mut lsm_iter = Self {
+            is_valid: iter.is_valid(),
+            inner: iter,
+            end_bound,
+            read_ts,
+            prev_key: None,
+        };
+        lsm_iter.move_to_visible_key()?;
+        Ok(lsm_iter)
+    }
+
+    fn past_end_bound(&self, uk: &[u8]) -> bool {
+        match &self.end_bound {
+            Bound::Included(end) => uk > end.as_ref(),
+            Bound::Excluded(end) => uk >= end.as_ref(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Skip past every version newer than `read_ts`, every tombstone, and every repeat of an
+    /// already-resolved user key, landing on the next visible, non-tombstone entry (or past
+    /// `end_bound`, in which case the iterator becomes invalid).
+    fn move_to_visible_key(&mut self) -> Result<()> {
+        loop {
+            if !self.inner.is_valid() {
+                self.is_valid = false;
+                return Ok(());
+            }
+
+            let uk = user_key(self.inner.key());
+            if self.past_end_bound(uk) {
+                self.is_valid = false;
+                return Ok(());
+            }
+
+            if self.prev_key.as_deref() == Some(uk) {
+                self.inner.next()?;
+                continue;
+            }
+
+            let (_, ts) = split_key_ts(self.inner.key());
+            if ts > self.read_ts {
+                // Not yet visible at `read_ts`; the next version of this user key (older) sorts
+                // right after it, so keep looking without marking this key resolved yet.
+                self.inner.next()?;
+                continue;
+            }
+
+            // First version of this user key with `ts <= read_ts`: the one visible to this read,
+            // whether it's a real value or a tombstone.
+            self.prev_key = Some(uk.to_vec());
+            if self.inner.value().is_empty() {
+                self.inner.next()?;
+                continue;
+            }
+            return Ok(());
+        }
+    }
+}
+
+impl StorageIterator for LsmIterator {
+    fn key(&self) -> &[u8] {
+        // Strip the MVCC ts suffix: `LsmStorage::scan` hands this straight to external callers,
+        // who know nothing about the on-disk encoding and expect back the key they wrote.
+        user_key(self.inner.key())
+    }
+
+    fn value(&self) -> &[u8] {
+        self.inner.value()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.is_valid
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.inner.next()?;
+        self.move_to_visible_key()
+    }
+}
+
+/// Wraps a `StorageIterator`, remembering whether it has errored so callers can't accidentally
+/// call `next`/`key`/`value` again afterward instead of treating the iterator as done.
+pub struct FusedIterator<I: StorageIterator> {
+    iter: I,
+    has_errored: bool,
+}
+
+impl<I: StorageIterator> FusedIterator<I> {
+    pub fn new(iter: I) -> Self {
+        Self {
+            iter,
+            has_errored: false,
+        }
+    }
+}
+
+impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
+    fn is_valid(&self) -> bool {
+        !self.has_errored && self.iter.is_valid()
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.is_valid(), "calling key() on an invalid iterator");
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.is_valid(), "calling value() on an invalid iterator");
+        self.iter.value()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        if self.has_errored {
+            bail!("cannot call next() on an iterator that has already errored");
+        }
+        if self.iter.is_valid() {
+            if let Err(e) = self.iter.next() {
+                self.has_errored = true;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
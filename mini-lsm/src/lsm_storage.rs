@@ -1,10 +1,15 @@
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, bail, Ok, Result};
 use bytes::Bytes;
 use parking_lot::{Mutex, RwLock};
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
@@ -12,11 +17,159 @@ use crate::iterators::StorageIterator;
 use crate::lsm_iterator::FusedIterator;
 use crate::lsm_iterator::LsmIterator;
 use crate::mem_table::{map_bound, Memtable};
-use crate::table::{SsTableBuilder, SsTableIterator};
+use crate::table::{
+    encode_key_with_ts, split_key_ts, user_key, CompressionType, FileObject, SsTableBuilder,
+    SsTableIterator,
+};
 use crate::{block::Block, table::SsTable};
 
 pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
 
+/// Compact L0 into L1 once L0 holds more tables than this.
+const L0_COMPACTION_TRIGGER: usize = 4;
+/// A level is compacted into the next once its total on-disk size exceeds
+/// `LEVEL_BASE_SIZE * 10^(level - 1)` bytes (level 1 == `levels[0]`).
+const LEVEL_BASE_SIZE: u64 = 64 * 1024 * 1024;
+/// Entries per output table during compaction; a rough stand-in for a byte-size target, since
+/// `SsTableBuilder` doesn't expose its buffered size.
+const COMPACTION_ENTRIES_PER_SST: usize = 4096;
+/// How often the background compaction thread checks whether anything needs compacting.
+const COMPACTION_INTERVAL: Duration = Duration::from_millis(500);
+/// Number of levels below L0 (L1..=L6); the last one drops tombstones once data reaches it.
+const MAX_LEVELS: usize = 6;
+/// Codec applied to every data block this store writes, on flush and on every compaction output
+/// alike. `SsTable::read_block` decodes each block's codec from that block's own tag, so this
+/// only governs what new blocks are written with, never what can be read back.
+const BLOCK_COMPRESSION: CompressionType = CompressionType::Lz4;
+/// On-disk filename for the manifest (see `Manifest`), sitting next to the `NNNNN.sst` files.
+const MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+/// Which on-disk sst ids belong to L0 vs. each level, as replayed from a `Manifest`.
+#[derive(Default)]
+struct RecoveredLevels {
+    l0: Vec<usize>,
+    levels: Vec<Vec<usize>>,
+}
+
+/// Append-only log recording which ids every flush/compaction added to or dropped from L0 and
+/// each level, so `LsmStorage::open` can restore the leveled structure instead of dumping every
+/// recovered `.sst` back into L0 (which would silently erase the leveling invariant on every
+/// restart). Replayed front-to-back on open; records are never compacted away, so the manifest's
+/// size is proportional to the number of flushes and compactions a store has done over its life.
+///
+/// Each line is one record: `FLUSH <id>`, `COMPACT_L0 <removed-list> <new_l1-list>`, or
+/// `COMPACT_LEVEL <level_idx> <removed-list> <new_next-list>`, where each `<...-list>` is a
+/// length-prefixed run of ids (`n id_1 .. id_n`). A record for a file is always appended only
+/// once that file is durably on disk, and old files are only unlinked once their replacement
+/// record is appended, so a crash can only ever leave behind an *unclaimed* on-disk `.sst`
+/// (handled by `LsmStorage::open` as a leftover to discard), never a `.sst` the manifest expects
+/// but that's missing.
+struct Manifest {
+    file: Mutex<File>,
+}
+
+impl Manifest {
+    /// Open (creating if necessary) the manifest at `path`, replaying any existing records into
+    /// `RecoveredLevels`. The returned `bool` is whether the manifest file didn't exist yet (as
+    /// opposed to existing but empty), which `LsmStorage::open` uses to tell a brand-new store
+    /// apart from one upgrading from before this file existed.
+    fn recover(path: &Path) -> Result<(Self, RecoveredLevels, bool)> {
+        let manifest_path = path.join(MANIFEST_FILE_NAME);
+        let is_fresh = !manifest_path.exists();
+        let existing = std::fs::read_to_string(&manifest_path).unwrap_or_default();
+
+        let mut recovered = RecoveredLevels::default();
+        for line in existing.lines().filter(|l| !l.is_empty()) {
+            Self::apply(&mut recovered, line)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&manifest_path)?;
+        Ok((Self { file: Mutex::new(file) }, recovered, is_fresh))
+    }
+
+    fn apply(recovered: &mut RecoveredLevels, line: &str) -> Result<()> {
+        let mut tokens = line.split_whitespace();
+        let tag = tokens.next().ok_or_else(|| anyhow!("empty manifest record"))?;
+        match tag {
+            "FLUSH" => {
+                recovered.l0.push(Self::next_id(&mut tokens)?);
+            }
+            "COMPACT_L0" => {
+                let removed = Self::next_list(&mut tokens)?;
+                let new_l1 = Self::next_list(&mut tokens)?;
+                recovered.l0.retain(|id| !removed.contains(id));
+                if recovered.levels.is_empty() {
+                    recovered.levels.push(Vec::new());
+                }
+                recovered.levels[0] = new_l1;
+            }
+            "COMPACT_LEVEL" => {
+                let level_idx = Self::next_id(&mut tokens)?;
+                let removed = Self::next_list(&mut tokens)?;
+                let new_next = Self::next_list(&mut tokens)?;
+                if let Some(level) = recovered.levels.get_mut(level_idx) {
+                    level.retain(|id| !removed.contains(id));
+                }
+                while recovered.levels.len() <= level_idx + 1 {
+                    recovered.levels.push(Vec::new());
+                }
+                recovered.levels[level_idx + 1] = new_next;
+            }
+            other => bail!("unknown manifest record tag {other:?}"),
+        }
+        Ok(())
+    }
+
+    fn next_id<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<usize> {
+        tokens
+            .next()
+            .ok_or_else(|| anyhow!("truncated manifest record"))?
+            .parse()
+            .map_err(|e| anyhow!("malformed manifest record: {e}"))
+    }
+
+    fn next_list<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<Vec<usize>> {
+        let len = Self::next_id(tokens)?;
+        (0..len).map(|_| Self::next_id(tokens)).collect()
+    }
+
+    fn encode_list(list: &[usize]) -> String {
+        std::iter::once(list.len().to_string())
+            .chain(list.iter().map(|id| id.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn append(&self, line: &str) -> Result<()> {
+        let mut file = self.file.lock();
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+
+    fn record_flush(&self, id: usize) -> Result<()> {
+        self.append(&format!("FLUSH {id}"))
+    }
+
+    fn record_compact_l0(&self, removed: &[usize], new_l1: &[usize]) -> Result<()> {
+        self.append(&format!(
+            "COMPACT_L0 {} {}",
+            Self::encode_list(removed),
+            Self::encode_list(new_l1)
+        ))
+    }
+
+    fn record_compact_level(&self, level_idx: usize, removed: &[usize], new_next: &[usize]) -> Result<()> {
+        self.append(&format!(
+            "COMPACT_LEVEL {level_idx} {} {}",
+            Self::encode_list(removed),
+            Self::encode_list(new_next)
+        ))
+    }
+}
+
 #[derive(Clone)]
 pub struct LsmStorageInner {
     /// The current memtable
@@ -26,10 +179,7 @@ pub struct LsmStorageInner {
     /// L0 SsTable, from earliest to latest
     l0_sstable: Vec<Arc<SsTable>>,
     /// L1 - L6 SsTables, sorted by key range.
-    #[allow(dead_code)]
     levels: Vec<Vec<Arc<SsTable>>>,
-    /// The next SsTable ID.
-    next_ssd_id: usize,
 }
 
 impl LsmStorageInner {
@@ -39,7 +189,36 @@ impl LsmStorageInner {
             imm_memtable: vec![],
             l0_sstable: vec![],
             levels: vec![],
-            next_ssd_id: 1,
+        }
+    }
+}
+
+/// A registered read timestamp, held for as long as some reader may still want a repeatable-read
+/// view as of it. While any `ReadSnapshot` is alive, compaction keeps at least one version `<=`
+/// its `read_ts` around for every key instead of collapsing each key down to its single newest
+/// version, so a long-lived snapshot can't have the version it would have seen compacted out from
+/// under it. Dropping it un-registers the timestamp, letting compaction reclaim older versions
+/// again once nothing still needs them.
+pub struct ReadSnapshot {
+    read_ts: u64,
+    watermarks: Arc<Mutex<BTreeMap<u64, usize>>>,
+}
+
+impl ReadSnapshot {
+    /// The timestamp this snapshot reads as of; pass to `get`/`scan`.
+    pub fn read_ts(&self) -> u64 {
+        self.read_ts
+    }
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        let mut watermarks = self.watermarks.lock();
+        if let Some(count) = watermarks.get_mut(&self.read_ts) {
+            *count -= 1;
+            if *count == 0 {
+                watermarks.remove(&self.read_ts);
+            }
         }
     }
 }
@@ -48,29 +227,209 @@ impl LsmStorageInner {
 pub struct LsmStorage {
     inner: Arc<RwLock<Arc<LsmStorageInner>>>,
     flush_lock: Mutex<()>,
+    /// Held for the duration of a single compaction pass so at most one runs at a time; shared
+    /// with the background compaction thread.
+    compaction_lock: Arc<Mutex<()>>,
     path: PathBuf,
     block_cache: Arc<BlockCache>,
+    /// Tracks which ids belong to L0 vs. each level across restarts; see `Manifest`.
+    manifest: Arc<Manifest>,
+    /// Next SsTable id to hand out, for both memtable flushes and compaction outputs.
+    next_sst_id: Arc<AtomicUsize>,
+    /// Next MVCC timestamp to hand out. Every `put`/`delete` is stamped with one on its way into
+    /// the memtable; `new_snapshot` hands out the current value as a read timestamp.
+    next_ts: Arc<AtomicU64>,
+    /// Read timestamps of every `ReadSnapshot` currently alive, each mapped to how many live
+    /// snapshots share it. Compaction treats the smallest key (if any) as the watermark below
+    /// which it's safe to drop all but the newest version of a key; see `ReadSnapshot`.
+    watermarks: Arc<Mutex<BTreeMap<u64, usize>>>,
+    /// Set by `Drop` to tell the background compaction thread to exit.
+    stop_compaction: Arc<AtomicBool>,
+    compaction_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+}
+
+impl Drop for LsmStorage {
+    fn drop(&mut self) {
+        self.stop_compaction.store(true, Ordering::SeqCst);
+    }
 }
 
 impl LsmStorage {
+    /// Open (or create) the LSM store at `path`, recovering `NNNNN.sst` files left over from a
+    /// previous run into the L0/level they belonged to, per the `MANIFEST` alongside them.
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            inner: Arc::new(RwLock::new(Arc::new(LsmStorageInner::create()))),
+        let path = path.as_ref().to_path_buf();
+        std::fs::create_dir_all(&path)?;
+        let block_cache = Arc::new(BlockCache::new(1 << 20)); // 4GB block cache
+
+        let mut sst_ids = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            let file_name = entry?.file_name();
+            if let Some(id) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".sst"))
+                .and_then(|id| id.parse::<usize>().ok())
+            {
+                sst_ids.push(id);
+            }
+        }
+        sst_ids.sort_unstable();
+
+        let (manifest, mut recovered, is_fresh_manifest) = Manifest::recover(&path)?;
+
+        let mut tables = HashMap::with_capacity(sst_ids.len());
+        for id in &sst_ids {
+            let sst_path = path.join(format!("{:05}.sst", id));
+            let file = FileObject::open(&sst_path)?;
+            tables.insert(*id, Arc::new(SsTable::open(*id, Some(block_cache.clone()), file, true)?));
+        }
+
+        // Every id the manifest claims for L0 or a level; anything on disk but not in here is a
+        // leftover the manifest never (or no longer) accounts for.
+        let claimed: HashSet<usize> = recovered
+            .l0
+            .iter()
+            .copied()
+            .chain(recovered.levels.iter().flatten().copied())
+            .collect();
+        for id in &sst_ids {
+            if claimed.contains(id) {
+                continue;
+            }
+            if is_fresh_manifest {
+                // This manifest has never recorded anything, so these files predate the manifest
+                // feature entirely (or this is the very first open of a fresh directory, in which
+                // case there are no stray ids anyway). Their original level can't be recovered;
+                // treat them as L0 and self-heal by recording them so future opens don't need
+                // this fallback.
+                manifest.record_flush(*id)?;
+                recovered.l0.push(*id);
+            } else {
+                // The manifest already has history, so this id is the product of a flush/
+                // compaction that crashed after writing its file but before (or without) the
+                // matching record landing, or of a compaction's old file never getting unlinked
+                // before a crash. Either way the manifest's view is authoritative; discard it.
+                let _ = std::fs::remove_file(path.join(format!("{:05}.sst", id)));
+                tables.remove(id);
+            }
+        }
+
+        let mut l0_sstable = Vec::with_capacity(recovered.l0.len());
+        for id in &recovered.l0 {
+            let table = tables
+                .get(id)
+                .cloned()
+                .ok_or_else(|| anyhow!("manifest references missing sst {id}"))?;
+            l0_sstable.push(table);
+        }
+
+        let mut levels = Vec::with_capacity(recovered.levels.len());
+        for level_ids in &recovered.levels {
+            let mut level = Vec::with_capacity(level_ids.len());
+            for id in level_ids {
+                let table = tables
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("manifest references missing sst {id}"))?;
+                level.push(table);
+            }
+            levels.push(level);
+        }
+
+        // Keys are stored as `user_key + !ts`; handing out a `next_ts` that collides with (or is
+        // older than) a ts already on disk would make `get`'s seek land on the wrong version or
+        // miss recovered data entirely, so recovery must resume one past the newest ts persisted
+        // anywhere in L0 or the levels.
+        let mut max_ts = 0u64;
+        for table in l0_sstable.iter().chain(levels.iter().flatten()) {
+            max_ts = max_ts.max(table.max_ts()?);
+        }
+
+        let mut inner = LsmStorageInner::create();
+        inner.l0_sstable = l0_sstable;
+        inner.levels = levels;
+        let next_sst_id = Arc::new(AtomicUsize::new(sst_ids.last().map_or(1, |id| id + 1)));
+
+        let storage = Self {
+            inner: Arc::new(RwLock::new(Arc::new(inner))),
             flush_lock: Mutex::new(()),
-            path: path.as_ref().to_path_buf(),
-            block_cache: Arc::new(BlockCache::new(1 << 20)), // 4GB block cache
-        })
+            compaction_lock: Arc::new(Mutex::new(())),
+            path,
+            block_cache,
+            manifest: Arc::new(manifest),
+            next_sst_id,
+            next_ts: Arc::new(AtomicU64::new(max_ts + 1)),
+            watermarks: Arc::new(Mutex::new(BTreeMap::new())),
+            stop_compaction: Arc::new(AtomicBool::new(false)),
+            compaction_thread: Mutex::new(None),
+        };
+        storage.spawn_compaction_thread();
+        Ok(storage)
+    }
+
+    fn compaction_context(&self) -> CompactionContext {
+        CompactionContext {
+            path: self.path.clone(),
+            block_cache: self.block_cache.clone(),
+            manifest: self.manifest.clone(),
+            next_sst_id: self.next_sst_id.clone(),
+        }
+    }
+
+    fn spawn_compaction_thread(&self) {
+        let inner = self.inner.clone();
+        let ctx = self.compaction_context();
+        let compaction_lock = self.compaction_lock.clone();
+        let watermarks = self.watermarks.clone();
+        let stop_compaction = self.stop_compaction.clone();
+
+        let handle = std::thread::spawn(move || {
+            while !stop_compaction.load(Ordering::SeqCst) {
+                std::thread::sleep(COMPACTION_INTERVAL);
+                if let Err(e) = run_compaction(&inner, &ctx, &compaction_lock, &watermarks) {
+                    eprintln!("compaction failed: {e}");
+                }
+            }
+        });
+        *self.compaction_thread.lock() = Some(handle);
+    }
+
+    /// Run one compaction pass immediately, without waiting for the background thread. Intended
+    /// for tests that want to assert on post-compaction state.
+    pub fn force_compact(&self) -> Result<()> {
+        run_compaction(
+            &self.inner,
+            &self.compaction_context(),
+            &self.compaction_lock,
+            &self.watermarks,
+        )
+    }
+
+    /// Returns a `ReadSnapshot` that captures every write committed so far. Pass its `read_ts()`
+    /// to `get`/`scan` for a repeatable-read view: later writes, even ones committed while the
+    /// snapshot is still in use, are invisible to it. Holding the returned value registers its
+    /// timestamp as a compaction watermark (see `ReadSnapshot`); drop it once done reading.
+    pub fn new_snapshot(&self) -> ReadSnapshot {
+        // `next_ts` is the ts the next write will get; every committed write has a smaller one.
+        let read_ts = self.next_ts.load(Ordering::SeqCst) - 1;
+        *self.watermarks.lock().entry(read_ts).or_insert(0) += 1;
+        ReadSnapshot {
+            read_ts,
+            watermarks: self.watermarks.clone(),
+        }
     }
 
-    /// Get a key from the storage.
-    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+    /// Get a key from the storage as of `read_ts`, or the latest committed version if `None`.
+    pub fn get(&self, key: &[u8], read_ts: Option<u64>) -> Result<Option<Bytes>> {
+        let read_ts = read_ts.unwrap_or_else(|| self.new_snapshot().read_ts());
+
         let snapshot = {
             let guard = self.inner.read();
             Arc::clone(&guard)
         }; // drop global lock here
 
         // search on the current memtable
-        if let Some(value) = snapshot.memtable.get(key) {
+        if let Some(value) = snapshot.memtable.get_with_ts(key, read_ts) {
             if value.is_empty() {
                 // found tomestone, return key not exists
                 return Ok(None);
@@ -80,7 +439,7 @@ impl LsmStorage {
 
         // search on immutable memetables.
         for memtable in snapshot.imm_memtable.iter().rev() {
-            if let Some(value) = memtable.get(key) {
+            if let Some(value) = memtable.get_with_ts(key, read_ts) {
                 if value.is_empty() {
                     // found tomestone, return key not exists
                     return Ok(None);
@@ -89,18 +448,45 @@ impl LsmStorage {
             }
         }
 
+        let lookup_key = encode_key_with_ts(key, read_ts);
         let mut iters = Vec::new();
         iters.reserve(snapshot.l0_sstable.len());
 
         for table in snapshot.l0_sstable.iter().rev() {
+            // skip tables whose bloom filter proves the key can't be present, avoiding a seek
+            if !table.may_contain(key) {
+                continue;
+            }
             iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
                 table.clone(),
-                key,
+                &lookup_key,
             )?));
         }
 
+        // L0 may still overlap in key range, so it's searched newest-table-first above; each
+        // level below it holds disjoint key ranges internally, but a level itself can still hold
+        // an older version of a key L0 holds a newer one of, so levels are searched newest (L1)
+        // first, deepest level last, keeping the overall priority newest-to-oldest.
+        for level in snapshot.levels.iter() {
+            for table in level.iter() {
+                if !table.may_contain(key) {
+                    continue;
+                }
+                iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
+                    table.clone(),
+                    &lookup_key,
+                )?));
+            }
+        }
+
         let iter = MergeIterator::create(iters);
-        if iter.is_valid() {
+        // `lookup_key` seeks to the newest version <= read_ts, but if every version present is
+        // newer than read_ts (or the key is absent entirely) the iterator lands on the next user
+        // key instead, so the user-key portion must still be checked before trusting the value.
+        if iter.is_valid() && user_key(iter.key()) == key {
+            if iter.value().is_empty() {
+                return Ok(None);
+            }
             return Ok(Some(Bytes::copy_from_slice(iter.value())));
         }
         Ok(None)
@@ -111,8 +497,9 @@ impl LsmStorage {
         assert!(!value.is_empty(), "value cannot be empty");
         assert!(!key.is_empty(), "key cannot be empty");
 
+        let ts = self.next_ts.fetch_add(1, Ordering::SeqCst);
         let guard = self.inner.read();
-        guard.memtable.put(key, value);
+        guard.memtable.put(&encode_key_with_ts(key, ts), value);
         Ok(())
     }
 
@@ -120,8 +507,9 @@ impl LsmStorage {
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         assert!(!key.is_empty(), "key cannot be empty");
 
+        let ts = self.next_ts.fetch_add(1, Ordering::SeqCst);
         let guard = self.inner.read();
-        guard.memtable.put(key, b"");
+        guard.memtable.put(&encode_key_with_ts(key, ts), b"");
         Ok(())
     }
 
@@ -133,7 +521,7 @@ impl LsmStorage {
     pub fn sync(&self) -> Result<()> {
         let _flush_lock = self.flush_lock.lock();
         let flush_memtable;
-        let sst_id;
+        let sst_id = self.next_sst_id.fetch_add(1, Ordering::SeqCst);
 
         // Move mutable memtable to immmutable memtables
         {
@@ -143,7 +531,6 @@ impl LsmStorage {
             let memtable = std::mem::replace(&mut snapshot.memtable, Arc::new(Memtable::create()));
 
             flush_memtable = memtable.clone();
-            sst_id = snapshot.next_ssd_id;
             // add the memetable to the immutable memtables
             snapshot.imm_memtable.push(memtable);
             // update the snapshot
@@ -153,7 +540,7 @@ impl LsmStorage {
         // At this point, the old memtable should be disabled for write, and all write threads
         // should be operating on the new memtable. We can safely flush the old memtable to
         // disk.
-        let mut builder: SsTableBuilder = SsTableBuilder::new(4096);
+        let mut builder: SsTableBuilder = SsTableBuilder::new(4096, BLOCK_COMPRESSION, true);
         flush_memtable.flush(&mut builder)?;
 
         let sst = Arc::new(builder.build(
@@ -162,6 +549,11 @@ impl LsmStorage {
             self.path_of_sst(sst_id),
         )?);
 
+        // The file is durably on disk at this point; record it in the manifest before it's
+        // visible anywhere else, so a crash before this line simply leaves an unclaimed `.sst`
+        // for the next `open` to discard rather than a table the manifest half-knows about.
+        self.manifest.record_flush(sst_id)?;
+
         // Add the flushed L0 table to the list
         {
             let mut guard = self.inner.write();
@@ -171,20 +563,24 @@ impl LsmStorage {
             snapshot.imm_memtable.pop();
             // add L0 table
             snapshot.l0_sstable.push(sst);
-            // update SST ID
-            snapshot.next_ssd_id += 1;
             // update the snapshot
             *guard = Arc::new(snapshot)
         }
         Ok(())
     }
 
-    /// Create an iterator over a range of keys.
+    /// Create an iterator over a range of keys, as of `read_ts` (or the latest committed version
+    /// if `None`). The returned iterator yields at most one entry per user key: the newest
+    /// version visible to `read_ts`, with tombstones and any version newer than `read_ts`
+    /// filtered out by `LsmIterator`.
     pub fn scan(
         &self,
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
+        read_ts: Option<u64>,
     ) -> Result<FusedIterator<LsmIterator>> {
+        let read_ts = read_ts.unwrap_or_else(|| self.new_snapshot().read_ts());
+
         let snapshot = {
             let guard = self.inner.read();
             Arc::clone(&guard)
@@ -192,41 +588,328 @@ impl LsmStorage {
 
         let mut memtable_iters = Vec::new();
         memtable_iters.reserve(snapshot.imm_memtable.len() + 1);
-        memtable_iters.push(Box::new(snapshot.memtable.scan(lower, upper)));
+        memtable_iters.push(Box::new(snapshot.memtable.scan_with_ts(lower, upper, read_ts)));
 
         for memtable in snapshot.imm_memtable.iter().rev() {
-            memtable_iters.push(Box::new(memtable.scan(lower, upper)));
+            memtable_iters.push(Box::new(memtable.scan_with_ts(lower, upper, read_ts)));
         }
 
         let memtable_iter = MergeIterator::create(memtable_iters);
 
-        let mut table_iters = Vec::new();
-
-        for table in snapshot.l0_sstable.iter().rev() {
-            let iter = match lower {
-                Bound::Included(key) => {
-                    SsTableIterator::create_and_seek_to_key(table.clone(), key)?
-                }
+        let seek_table = |table: &Arc<SsTable>| -> Result<SsTableIterator> {
+            Ok(match lower {
+                Bound::Included(key) => SsTableIterator::create_and_seek_to_key(
+                    table.clone(),
+                    &encode_key_with_ts(key, read_ts),
+                )?,
 
                 Bound::Excluded(key) => {
-                    let mut iter = SsTableIterator::create_and_seek_to_key(table.clone(), key)?;
-                    if iter.is_valid() && iter.key() == key {
+                    // Seeking with ts=0 yields the largest possible encoded suffix for `key`, so
+                    // this lands just past every version of it regardless of `read_ts`.
+                    let mut iter = SsTableIterator::create_and_seek_to_key(
+                        table.clone(),
+                        &encode_key_with_ts(key, 0),
+                    )?;
+                    if iter.is_valid() && user_key(iter.key()) == key {
                         iter.next()?;
                     }
                     iter
                 }
                 Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table.clone())?,
-            };
-            table_iters.push(Box::new(iter));
+            })
+        };
+
+        let mut table_iters = Vec::new();
+
+        for table in snapshot.l0_sstable.iter().rev() {
+            table_iters.push(Box::new(seek_table(table)?));
+        }
+
+        // Same priority order as `get`: L0 overlaps in key range so it's searched newest-first,
+        // while each level below is internally disjoint but searched newest level (L1) first,
+        // deepest level last, so the overall ordering in `table_iters` stays newest-to-oldest.
+        for level in snapshot.levels.iter() {
+            for table in level.iter() {
+                table_iters.push(Box::new(seek_table(table)?));
+            }
         }
 
         let table_iter = MergeIterator::create(table_iters);
 
         let iter = TwoMergeIterator::create(memtable_iter, table_iter)?;
 
-        Ok(FusedIterator::new(LsmIterator::new(
+        Ok(FusedIterator::new(LsmIterator::new_with_read_ts(
             iter,
             map_bound(upper),
+            read_ts,
         )?))
     }
 }
+
+/// Everything a compaction pass needs besides the live `LsmStorageInner` snapshot, bundled so
+/// `run_compaction` and its helpers take one argument instead of threading `path`/`block_cache`/
+/// `manifest`/`next_sst_id` through separately.
+struct CompactionContext {
+    path: PathBuf,
+    block_cache: Arc<BlockCache>,
+    manifest: Arc<Manifest>,
+    next_sst_id: Arc<AtomicUsize>,
+}
+
+/// Run one compaction pass: L0 takes priority over any level once it's over threshold, since
+/// shrinking L0 is what keeps point lookups fast; otherwise the first level found over its size
+/// threshold is compacted into the next one down.
+fn run_compaction(
+    inner: &Arc<RwLock<Arc<LsmStorageInner>>>,
+    ctx: &CompactionContext,
+    compaction_lock: &Mutex<()>,
+    watermarks: &Mutex<BTreeMap<u64, usize>>,
+) -> Result<()> {
+    let _guard = compaction_lock.lock();
+
+    let snapshot = {
+        let guard = inner.read();
+        Arc::clone(&guard)
+    };
+
+    // The oldest read timestamp any live `ReadSnapshot` still needs; compaction must keep at
+    // least one version `<=` this around for every key so no live snapshot loses the version it
+    // would have seen. With no live snapshots, every key can be collapsed to its newest version.
+    let watermark = watermarks.lock().keys().next().copied().unwrap_or(u64::MAX);
+
+    if snapshot.l0_sstable.len() > L0_COMPACTION_TRIGGER {
+        return compact_l0(inner, ctx, &snapshot, watermark);
+    }
+
+    // L6 (`levels[MAX_LEVELS - 1]`) is the configured bottom level and never compacts further
+    // down, so only levels that still have a "next" level to compact into are considered here.
+    for level_idx in 0..snapshot.levels.len().min(MAX_LEVELS - 1) {
+        let level_bytes: u64 = snapshot.levels[level_idx].iter().map(|t| t.file_size()).sum();
+        let threshold = LEVEL_BASE_SIZE.saturating_mul(10u64.pow(level_idx as u32));
+        if level_bytes > threshold {
+            return compact_level(inner, ctx, &snapshot, level_idx, watermark);
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge every L0 table plus all of (current) L1 into fresh L1 tables. L1 is never the bottom
+/// level (see `MAX_LEVELS`/`compact_level`), so tombstones are always carried through here —
+/// dropping one would resurrect whatever older value for the same key still sits in L2+.
+fn compact_l0(
+    inner: &Arc<RwLock<Arc<LsmStorageInner>>>,
+    ctx: &CompactionContext,
+    snapshot: &LsmStorageInner,
+    watermark: u64,
+) -> Result<()> {
+    let l1_tables = snapshot.levels.first().cloned().unwrap_or_default();
+
+    let mut iters = Vec::with_capacity(snapshot.l0_sstable.len() + l1_tables.len());
+    for table in snapshot.l0_sstable.iter().rev() {
+        iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+            table.clone(),
+        )?));
+    }
+    for table in l1_tables.iter() {
+        iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+            table.clone(),
+        )?));
+    }
+    let merged = MergeIterator::create(iters);
+    let new_l1 = drain_into_ssts(merged, false, watermark, ctx)?;
+
+    let compacted_l0: HashSet<usize> = snapshot.l0_sstable.iter().map(|t| t.sst_id()).collect();
+    let mut removed_ids: Vec<usize> = compacted_l0.iter().copied().collect();
+    removed_ids.extend(l1_tables.iter().map(|t| t.sst_id()));
+    let new_l1_ids: Vec<usize> = new_l1.iter().map(|t| t.sst_id()).collect();
+
+    // The new L1 tables are durably on disk at this point; record the swap before anything else
+    // touches L0/L1 membership, so a crash before the old files are unlinked below just leaves
+    // them unclaimed for the next `open` to discard instead of double-counted.
+    ctx.manifest.record_compact_l0(&removed_ids, &new_l1_ids)?;
+
+    // Swap in the new snapshot. Only the exact L0 tables we just compacted are dropped, so a
+    // concurrent `sync()` flushing a new L0 table between our snapshot read and this write isn't
+    // lost.
+    {
+        let mut guard = inner.write();
+        let mut new_snapshot = guard.as_ref().clone();
+        new_snapshot
+            .l0_sstable
+            .retain(|t| !compacted_l0.contains(&t.sst_id()));
+        if new_snapshot.levels.is_empty() {
+            new_snapshot.levels.push(Vec::new());
+        }
+        new_snapshot.levels[0] = new_l1;
+        *guard = Arc::new(new_snapshot);
+    }
+
+    for id in removed_ids {
+        let _ = std::fs::remove_file(ctx.path.join(format!("{:05}.sst", id)));
+    }
+    Ok(())
+}
+
+/// Pick the first table in `levels[level_idx]` and merge it into whichever tables in
+/// `levels[level_idx + 1]` overlap its key range.
+fn compact_level(
+    inner: &Arc<RwLock<Arc<LsmStorageInner>>>,
+    ctx: &CompactionContext,
+    snapshot: &LsmStorageInner,
+    level_idx: usize,
+    watermark: u64,
+) -> Result<()> {
+    if level_idx + 1 >= MAX_LEVELS {
+        // L6 is the configured bottom level; there's no level below it to compact into.
+        return Ok(());
+    }
+
+    let victim = match snapshot.levels[level_idx].first().cloned() {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    let victim_first = victim.first_key().clone();
+    let victim_last = victim.last_key()?;
+
+    let next_level = snapshot
+        .levels
+        .get(level_idx + 1)
+        .cloned()
+        .unwrap_or_default();
+    // Compare user-key ranges, not the raw MVCC-encoded bytes: two tables can share a user key
+    // while still landing on opposite sides of the exact encoded byte boundary (their versions'
+    // timestamps differ), which would wrongly be judged disjoint and break the per-level
+    // disjoint-key-range invariant.
+    let (overlapping, disjoint): (Vec<_>, Vec<_>) = next_level.into_iter().partition(|t| {
+        let first = t.first_key();
+        let last = t.last_key().unwrap_or_else(|_| first.clone());
+        user_key(&last) >= user_key(&victim_first) && user_key(first) <= user_key(&victim_last)
+    });
+
+    let mut iters = Vec::with_capacity(1 + overlapping.len());
+    iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+        victim.clone(),
+    )?));
+    for table in &overlapping {
+        iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+            table.clone(),
+        )?));
+    }
+    let merged = MergeIterator::create(iters);
+
+    let is_bottom_level = level_idx + 1 >= MAX_LEVELS - 1;
+    let new_tables = drain_into_ssts(merged, is_bottom_level, watermark, ctx)?;
+
+    let mut removed_ids = vec![victim.sst_id()];
+    removed_ids.extend(overlapping.iter().map(|t| t.sst_id()));
+
+    let mut next = disjoint;
+    next.extend(new_tables);
+    next.sort_by(|a, b| a.first_key().cmp(b.first_key()));
+    let next_ids: Vec<usize> = next.iter().map(|t| t.sst_id()).collect();
+
+    // Same ordering rationale as `compact_l0`: record the swap once the new tables are durable,
+    // before the old ones are unlinked.
+    ctx.manifest
+        .record_compact_level(level_idx, &removed_ids, &next_ids)?;
+
+    {
+        let mut guard = inner.write();
+        let mut new_snapshot = guard.as_ref().clone();
+
+        if let Some(level) = new_snapshot.levels.get_mut(level_idx) {
+            level.retain(|t| t.sst_id() != victim.sst_id());
+        }
+
+        while new_snapshot.levels.len() <= level_idx + 1 {
+            new_snapshot.levels.push(Vec::new());
+        }
+        new_snapshot.levels[level_idx + 1] = next;
+
+        *guard = Arc::new(new_snapshot);
+    }
+
+    for id in removed_ids {
+        let _ = std::fs::remove_file(ctx.path.join(format!("{:05}.sst", id)));
+    }
+    Ok(())
+}
+
+/// Drain a merged iterator into one or more new SSTables of roughly
+/// `COMPACTION_ENTRIES_PER_SST` entries each, optionally dropping tombstones (empty values).
+///
+/// Every version of a user key sorts together with the newest (largest ts) first. A version is
+/// kept if it's `>= watermark` (some live `ReadSnapshot` could still need exactly that version)
+/// or if it's the first version `< watermark` seen for that user key (the newest one any
+/// snapshot at or below the watermark could ever select; everything older than it is truly dead,
+/// since no live snapshot reads below the watermark). `watermark` is `u64::MAX` when no snapshot
+/// is live, which collapses every key down to just its newest version.
+fn drain_into_ssts(
+    mut iter: MergeIterator<SsTableIterator>,
+    drop_tombstones: bool,
+    watermark: u64,
+    ctx: &CompactionContext,
+) -> Result<Vec<Arc<SsTable>>> {
+    let mut output = Vec::new();
+    let mut builder = SsTableBuilder::new(4096, BLOCK_COMPRESSION, true);
+    let mut count = 0usize;
+    let mut last_key: Option<Vec<u8>> = None;
+    let mut kept_version_below_watermark = false;
+
+    while iter.is_valid() {
+        let key = iter.key();
+        let uk = user_key(key);
+        let (_, ts) = split_key_ts(key);
+
+        if last_key.as_deref() != Some(uk) {
+            last_key = Some(uk.to_vec());
+            kept_version_below_watermark = false;
+        }
+
+        let keep = if ts >= watermark {
+            true
+        } else if !kept_version_below_watermark {
+            kept_version_below_watermark = true;
+            true
+        } else {
+            false
+        };
+
+        // A tombstone below the watermark is only ever dropped once it's also the version kept
+        // above (i.e. it's the newest a below-watermark reader could see); one still `>= the
+        // watermark` must be kept regardless so a snapshot that can see it isn't fooled into
+        // thinking the key still has an older, non-deleted value.
+        if keep && !(drop_tombstones && ts < watermark && iter.value().is_empty()) {
+            builder.add(key, iter.value())?;
+            count += 1;
+        }
+
+        if count >= COMPACTION_ENTRIES_PER_SST {
+            output.push(flush_builder(builder, ctx)?);
+            builder = SsTableBuilder::new(4096, BLOCK_COMPRESSION, true);
+            count = 0;
+        }
+
+        iter.next()?;
+    }
+
+    if count > 0 {
+        output.push(flush_builder(builder, ctx)?);
+    }
+
+    Ok(output)
+}
+
+fn flush_builder(builder: SsTableBuilder, ctx: &CompactionContext) -> Result<Arc<SsTable>> {
+    let id = ctx.next_sst_id.fetch_add(1, Ordering::SeqCst);
+    let sst_path = ctx.path.join(format!("{:05}.sst", id));
+    Ok(Arc::new(builder.build(
+        id,
+        Some(ctx.block_cache.clone()),
+        sst_path,
+    )?))
+}
+
+#[cfg(test)]
+mod lsm_storage_test;
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use anyhow::Result;
+use bytes::Bytes;
+use parking_lot::RwLock;
+
+use crate::iterators::StorageIterator;
+use crate::table::{encode_key_with_ts, split_key_ts, user_key, SsTableBuilder};
+
+/// Convert a `Bound<&[u8]>` into an owned `Bound<Bytes>`, for callers (like `LsmIterator`) that
+/// need to hold onto a scan's upper bound past the borrow the `&[u8]` came from.
+pub fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
+    match bound {
+        Bound::Included(key) => Bound::Included(Bytes::copy_from_slice(key)),
+        Bound::Excluded(key) => Bound::Excluded(Bytes::copy_from_slice(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// An in-memory table of not-yet-flushed writes. Keys are stored already MVCC-encoded
+/// (`user_key + !ts`, see [`encode_key_with_ts`]), so a `Memtable`'s iteration order matches an
+/// `SsTable`'s: entries for the same user key sort together, newest version first.
+#[derive(Default)]
+pub struct Memtable {
+    map: RwLock<BTreeMap<Bytes, Bytes>>,
+}
+
+impl Memtable {
+    /// Create an empty memtable.
+    pub fn create() -> Self {
+        Self::default()
+    }
+
+    /// Insert (or overwrite) `key` (already MVCC-encoded) with `value`. An empty `value` records
+    /// a tombstone, the same convention `LsmStorage::delete` writes.
+    pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.map
+            .write()
+            .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
+    }
+
+    /// Look up the newest version of `key` visible at `read_ts`, if any. Callers check
+    /// `Bytes::is_empty` themselves to distinguish a tombstone from a missing key, the same
+    /// convention `LsmStorage::get` uses for on-disk lookups.
+    pub fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Option<Bytes> {
+        let map = self.map.read();
+        // The first stored key `>=` this bound is the newest version of `key` with `ts <=
+        // read_ts` (larger ts sorts first, see `encode_key_with_ts`).
+        let lookup = Bytes::from(encode_key_with_ts(key, read_ts));
+        let (found_key, value) = map.range(lookup..).next()?;
+        if user_key(found_key) == key {
+            Some(value.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Flush every version of every key in this memtable into `builder`, in the same
+    /// newest-version-first-per-user-key order an `SsTable` expects.
+    pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
+        for (key, value) in self.map.read().iter() {
+            builder.add(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Create an iterator over the user-key range from `lower` to `upper` (each bound inclusive,
+    /// exclusive or unbounded, as given), yielding at most one entry
+    /// per user key: the newest version visible to `read_ts`. Tombstones are passed through (as
+    /// empty values) for `LsmIterator` to filter.
+    pub fn scan_with_ts(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> MemtableIterator {
+        let in_range = |uk: &[u8]| -> bool {
+            let low_ok = match lower {
+                Bound::Included(b) => uk >= b,
+                Bound::Excluded(b) => uk > b,
+                Bound::Unbounded => true,
+            };
+            let high_ok = match upper {
+                Bound::Included(b) => uk <= b,
+                Bound::Excluded(b) => uk < b,
+                Bound::Unbounded => true,
+            };
+            low_ok && high_ok
+        };
+
+        // Encoded keys for a user key sort by `(user_key, ts desc)`, so `ts=u64::MAX`/`ts=0`
+        // give the smallest/largest possible encoding of that user key; bounding `map.range` with
+        // those narrows the walk to roughly the requested range instead of the whole memtable,
+        // the same way `get_with_ts` bounds its single-key lookup. `in_range` above still does
+        // the exact filtering, since these bounds are deliberately a little loose at the edges.
+        let range_start = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                Bound::Included(Bytes::from(encode_key_with_ts(key, u64::MAX)))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let range_end = match upper {
+            Bound::Included(key) => Bound::Included(Bytes::from(encode_key_with_ts(key, 0))),
+            Bound::Excluded(key) => Bound::Excluded(Bytes::from(encode_key_with_ts(key, u64::MAX))),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let map = self.map.read();
+        let mut entries = Vec::new();
+        // Versions of a user key appear together, newest (largest ts) first; the first one seen
+        // `<= read_ts` is the version visible to this scan, so every key that's already produced
+        // (or ruled entirely invisible/out-of-range) one is skipped until the user key changes.
+        let mut current_key: Option<Vec<u8>> = None;
+        let mut resolved = false;
+        for (key, value) in map.range((range_start, range_end)) {
+            let uk = user_key(key);
+            if current_key.as_deref() != Some(uk) {
+                current_key = Some(uk.to_vec());
+                resolved = false;
+            }
+            if resolved || !in_range(uk) {
+                continue;
+            }
+            let (_, ts) = split_key_ts(key);
+            if ts > read_ts {
+                continue;
+            }
+            resolved = true;
+            entries.push((key.clone(), value.clone()));
+        }
+
+        MemtableIterator { entries, index: 0 }
+    }
+}
+
+/// Iterates the deduped, read-ts-filtered snapshot a [`Memtable::scan_with_ts`] call collects.
+pub struct MemtableIterator {
+    entries: Vec<(Bytes, Bytes)>,
+    index: usize,
+}
+
+impl StorageIterator for MemtableIterator {
+    fn key(&self) -> &[u8] {
+        &self.entries[self.index].0
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.entries[self.index].1
+    }
+
+    fn is_valid(&self) -> bool {
+        self.index < self.entries.len()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+}
@@ -5,8 +5,10 @@ use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use bytes::{Buf, BufMut, Bytes};
+use crc32c::crc32c;
+use memmap2::Mmap;
 
 use crate::block::Block;
 use crate::lsm_storage::BlockCache;
@@ -16,15 +18,195 @@ pub use iterator::SsTableIterator;
 
 /*
 SST like below
-| data block | data block | data block | data block | meta block | meta block offset (u32) |
+| data block | ... | meta block | meta crc32 (u32) | filter block | footer (9B) | footer crc32 (u32) |
 
 
 meta block:
 vec of BlockMeta
 includes the first key in each block and the offset of each block.
 |offset as u32| first key len as u16|first key|
+
+data block (on disk, as written by SsTableBuilder::finish_block):
+|compression tag (1B)|uncompressed len (u32)|compressed payload|block crc32 (u32)|
+where the decompressed payload is exactly what Block::encode produces. The crc32 (castagnoli)
+covers everything in the block's record up to (not including) itself.
+
+filter block:
+|k (u8)|bit array|
+a bloom filter over every key added to the table, consulted by `SsTable::may_contain`
+before bothering to seek into the table's data blocks.
+
+footer (9B): |meta block offset (u32)|filter block offset (u32)|compression (u8)|
+followed by a crc32 of those 9 bytes. Every crc32 check can be disabled per-table via
+`verify_checksums` for read-heavy, latency-sensitive workloads that accept the (small) risk
+of silently reading corrupted data.
+
+Every key stored in a block (and therefore every `BlockMeta::first_key`) is the MVCC-encoded
+`user_key + !ts` produced by `encode_key_with_ts`, so within a user key, newer versions sort
+before older ones; the bloom filter is the one exception and is built over bare user keys, since
+a single filter entry should cover every version of a key.
 */
 
+/// Target bits-of-filter per key; yields roughly a 1% false positive rate at k ~= 7.
+pub const BLOOM_BITS_PER_KEY: usize = 10;
+
+/// FNV-1a, used to derive the bloom filter probes for a key.
+fn fnv1a_32(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for &b in data {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Hash a user key into the 32-bit value `BloomFilter` probes are built from.
+pub fn hash_key(key: &[u8]) -> u32 {
+    fnv1a_32(key)
+}
+
+/// Number of trailing bytes the MVCC timestamp occupies in an on-disk key.
+pub const TS_LEN: usize = std::mem::size_of::<u64>();
+
+/// Encode `user_key` and `ts` into the key actually stored in blocks: `user_key + !ts`, with the
+/// timestamp bitwise-inverted so that, for a fixed user key, a *larger* `ts` sorts *first*. This
+/// lets a seek for `(user_key, read_ts)` land directly on the newest version visible to
+/// `read_ts`, since every visible version then sorts at or after the seek target and every
+/// version newer than `read_ts` sorts before it.
+pub fn encode_key_with_ts(user_key: &[u8], ts: u64) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(user_key.len() + TS_LEN);
+    encoded.extend_from_slice(user_key);
+    encoded.put_u64(!ts);
+    encoded
+}
+
+/// Split an on-disk key back into its user key and timestamp.
+pub fn split_key_ts(key: &[u8]) -> (&[u8], u64) {
+    let (user_key, mut ts_bytes) = key.split_at(key.len() - TS_LEN);
+    (user_key, !ts_bytes.get_u64())
+}
+
+/// The user key portion of an on-disk key, discarding the timestamp.
+pub fn user_key(key: &[u8]) -> &[u8] {
+    &key[..key.len() - TS_LEN]
+}
+
+/// A bloom filter over a set of key hashes, following the leveldb `filter_block` design: a bit
+/// array sized `bits_per_key * num_keys`, with `k` double-hashed probes per key derived from a
+/// single 32-bit hash (`h1`) by multiplying and bit-rotating it into a second hash (`h2`), then testing/setting
+/// bits at `(h1 + i * h2) % nbits` for `i in 0..k`.
+#[derive(Debug, Default)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    k: u8,
+}
+
+impl BloomFilter {
+    fn probe(key_hash: u32, k: u8, nbits: usize, mut set: impl FnMut(usize)) {
+        let h2 = key_hash.wrapping_mul(0x9e37_79b9).rotate_left(15);
+        let mut h = key_hash;
+        for _ in 0..k {
+            set(h as usize % nbits);
+            h = h.wrapping_add(h2);
+        }
+    }
+
+    /// Build a filter sized for `key_hashes.len()` keys at `bits_per_key` bits/key.
+    pub fn build(key_hashes: &[u32], bits_per_key: usize) -> Self {
+        let k = ((bits_per_key as f64) * std::f64::consts::LN_2)
+            .round()
+            .clamp(1.0, 30.0) as u8;
+
+        let nbits = (key_hashes.len() * bits_per_key).max(64);
+        let nbytes = nbits.div_ceil(8);
+        let nbits = nbytes * 8;
+        let mut bits = vec![0u8; nbytes];
+
+        for &key_hash in key_hashes {
+            Self::probe(key_hash, k, nbits, |bit| bits[bit / 8] |= 1 << (bit % 8));
+        }
+
+        Self { bits, k }
+    }
+
+    /// Whether `key_hash` might be present. False positives are possible; false negatives are not.
+    pub fn may_contain(&self, key_hash: u32) -> bool {
+        if self.bits.is_empty() {
+            return false;
+        }
+        let nbits = self.bits.len() * 8;
+        let mut found = true;
+        Self::probe(key_hash, self.k, nbits, |bit| {
+            found &= self.bits[bit / 8] & (1 << (bit % 8)) != 0;
+        });
+        found
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.bits.len() + 1);
+        buf.push(self.k);
+        buf.extend_from_slice(&self.bits);
+        buf
+    }
+
+    fn decode(data: &[u8]) -> Self {
+        Self {
+            k: data[0],
+            bits: data[1..].to_vec(),
+        }
+    }
+}
+
+/// The codec used to compress each data block before it is written to disk.
+///
+/// The block cache always holds decompressed `Arc<Block>`s, so this only
+/// affects on-disk footprint and the cost of `SsTable::read_block`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionType {
+    None,
+    Snappy,
+    Lz4,
+}
+
+impl CompressionType {
+    fn as_u8(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Snappy => 1,
+            CompressionType::Lz4 => 2,
+        }
+    }
+
+    fn from_u8(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(CompressionType::None),
+            1 => Ok(CompressionType::Snappy),
+            2 => Ok(CompressionType::Lz4),
+            _ => bail!("unknown compression type tag {}", tag),
+        }
+    }
+
+    /// Compress a block's encoded `data+restarts` payload.
+    pub(crate) fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            CompressionType::None => Ok(data.to_vec()),
+            CompressionType::Snappy => Ok(snap::raw::Encoder::new().compress_vec(data)?),
+            CompressionType::Lz4 => Ok(lz4::block::compress(data, None, false)?),
+        }
+    }
+
+    /// Decompress a block back into its encoded `data+restarts` payload.
+    pub(crate) fn decompress(self, data: &[u8], uncompressed_len: usize) -> Result<Vec<u8>> {
+        match self {
+            CompressionType::None => Ok(data.to_vec()),
+            CompressionType::Snappy => Ok(snap::raw::Decoder::new().decompress_vec(data)?),
+            CompressionType::Lz4 => {
+                Ok(lz4::block::decompress(data, Some(uncompressed_len as i32))?)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
     /// Offset of this data block
@@ -73,33 +255,51 @@ impl BlockMeta {
         block_meta
     }
 }
-/// File name and total block with byte unite
+/// A file backing an SSTable: the open handle, its length, and (when available) a
+/// memory-mapped view of its contents so `read` can slice the mapping instead of issuing a
+/// `pread` syscall per block.
 #[derive(Debug)]
-pub struct FileObject(File, u64);
+pub struct FileObject {
+    file: File,
+    mmap: Option<Mmap>,
+    size: u64,
+}
 
 impl FileObject {
     /// read from offset and length is len
     pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let start = offset as usize;
+        let end = start + len as usize;
+
+        if let Some(mmap) = &self.mmap {
+            return Ok(mmap[start..end].to_vec());
+        }
+
+        // fall back to a positioned read, e.g. if mmap-ing this file failed
         use std::os::unix::fs::FileExt;
         let mut data = vec![0; len as usize];
-        self.0.read_exact_at(&mut data[..], offset)?;
+        self.file.read_exact_at(&mut data[..], offset)?;
         Ok(data)
     }
 
     pub fn size(&self) -> u64 {
-        self.1
+        self.size
     }
 
     pub fn new(path: &Path, data: Vec<u8>) -> Result<Self> {
         std::fs::write(path, &data)?;
-        Ok(FileObject(
-            File::options().read(true).write(false).open(path)?,
-            data.len() as u64,
-        ))
+        Self::open(path)
     }
 
-    pub fn open(_path: &Path) -> Result<Self> {
-        unimplemented!()
+    /// Open an existing `.sst`, backing reads with a memory-mapped region where possible.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::options().read(true).write(false).open(path)?;
+        let size = file.metadata()?.len();
+        // SAFETY: the mapping may be invalidated by concurrent truncation of the underlying
+        // file, which we don't do to SST files after they're written; fall back to pread
+        // on any platform/file where the mapping can't be created at all.
+        let mmap = unsafe { Mmap::map(&file) }.ok();
+        Ok(Self { file, mmap, size })
     }
 }
 
@@ -111,30 +311,65 @@ pub struct SsTable {
     block_meta_offset: usize,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
+    filter: BloomFilter,
+    /// Whether `read_block` recomputes and checks each block's crc32 before decoding it.
+    verify_checksums: bool,
 }
 
 impl SsTable {
     #[cfg(test)]
     pub(crate) fn open_for_test(file: FileObject) -> Result<Self> {
-        Self::open(0, None, file)
+        Self::open(0, None, file, true)
     }
 
     /// Open SSTable from a file
-    pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
+    pub fn open(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        verify_checksums: bool,
+    ) -> Result<Self> {
         let len = file.size();
-        // meta block offset (u32) u32 4个字节
-        let raw_meta_offset = file.read(len - 4, 4)?;
-        let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
-        let raw_meta = file.read(block_meta_offset, len - 4 - block_meta_offset)?;
+        // footer (9B) + its crc32 (4B), the last 13 bytes of the file
+        let raw_footer = file.read(len - 13, 13)?;
+        let (footer_bytes, footer_crc_bytes) = raw_footer.split_at(9);
+        if verify_checksums && crc32c(footer_bytes) != (&footer_crc_bytes[..]).get_u32() {
+            bail!("footer checksum mismatch in sst {}", id);
+        }
+        let mut footer_buf = footer_bytes;
+        let block_meta_offset = footer_buf.get_u32() as u64;
+        let filter_offset = footer_buf.get_u32() as u64;
+        // Each block carries its own compression tag (see `read_block`), so this footer tag is
+        // only a validity check on open, not something we need to hold onto.
+        let _ = CompressionType::from_u8(footer_buf.get_u8())?;
+
+        // meta block, followed by its own crc32, spans up to filter_offset
+        let raw_meta_section = file.read(block_meta_offset, filter_offset - block_meta_offset)?;
+        let (raw_meta, meta_crc_bytes) = raw_meta_section.split_at(raw_meta_section.len() - 4);
+        if verify_checksums && crc32c(raw_meta) != (&meta_crc_bytes[..]).get_u32() {
+            bail!("meta block checksum mismatch in sst {}", id);
+        }
+
+        let raw_filter = file.read(filter_offset, len - 13 - filter_offset)?;
         Ok(Self {
             file,
-            block_metas: BlockMeta::decode_block_meta(&raw_meta[..]),
+            block_metas: BlockMeta::decode_block_meta(raw_meta),
             block_meta_offset: block_meta_offset as usize,
             id,
             block_cache,
+            filter: BloomFilter::decode(&raw_filter),
+            verify_checksums,
         })
     }
 
+    /// Whether this table might contain `user_key` (ignoring timestamp; the filter is built over
+    /// user keys so one bloom probe covers every version of a key). A `false` result means the
+    /// table definitely does not contain it, letting callers skip seeking into its blocks
+    /// entirely.
+    pub fn may_contain(&self, user_key: &[u8]) -> bool {
+        self.filter.may_contain(hash_key(user_key))
+    }
+
     /// Read a block from the disk
     pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
         let offset = self.block_metas[block_idx].offset;
@@ -143,9 +378,36 @@ impl SsTable {
             .get(block_idx + 1)
             .map_or(self.block_meta_offset, |x| x.offset);
 
-        let block_data = self
+        let raw = self
             .file
             .read(offset as u64, (offset_end - offset) as u64)?;
+        let (record, crc_bytes) = raw.split_at(raw.len() - 4);
+
+        if self.verify_checksums {
+            let expected = (&crc_bytes[..]).get_u32();
+            let actual = crc32c(record);
+            if actual != expected {
+                bail!(
+                    "checksum mismatch for block {} in sst {}: expected {}, got {}",
+                    block_idx,
+                    self.id,
+                    expected,
+                    actual
+                );
+            }
+        }
+
+        let tag = record[0];
+        let compression = CompressionType::from_u8(tag).map_err(|e| {
+            anyhow!(
+                "corrupt compression tag for block {} in sst {}: {}",
+                block_idx,
+                self.id,
+                e
+            )
+        })?;
+        let uncompressed_len = (&record[1..5]).get_u32() as usize;
+        let block_data = compression.decompress(&record[5..], uncompressed_len)?;
 
         Ok(Arc::new(Block::decode(&block_data[..])))
     }
@@ -174,6 +436,54 @@ impl SsTable {
     pub fn num_of_blocks(&self) -> usize {
         self.block_metas.len()
     }
+
+    /// The id this table was built or opened with.
+    pub fn sst_id(&self) -> usize {
+        self.id
+    }
+
+    /// Size of the table's backing file, used to decide when a level has grown past its
+    /// compaction threshold.
+    pub fn file_size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// The smallest key in the table, encoded as `user_key + !ts` (see [`encode_key_with_ts`]).
+    pub fn first_key(&self) -> &Bytes {
+        &self.block_metas[0].first_key
+    }
+
+    /// The largest key in the table, encoded like [`SsTable::first_key`]. Unlike `first_key`,
+    /// this isn't cached in `BlockMeta`, so it costs a block read; only call it off the hot path
+    /// (e.g. during compaction).
+    pub fn last_key(&self) -> Result<Bytes> {
+        let last_block = self.read_block_cached(self.num_of_blocks() - 1)?;
+        let mut iter = crate::block::BlockIterator::create_and_seek_to_first(last_block);
+        let mut last_key = Vec::new();
+        while iter.is_valid() {
+            last_key = iter.key().to_vec();
+            iter.next();
+        }
+        Ok(Bytes::from(last_key))
+    }
+
+    /// The largest MVCC timestamp stamped on any key in this table. Unlike `last_key`, the
+    /// largest ts isn't necessarily on the table's last encoded key (a different user key
+    /// entirely may carry a newer ts), so every block is scanned; used only during
+    /// `LsmStorage::open` recovery, never on a read path.
+    pub fn max_ts(&self) -> Result<u64> {
+        let mut max_ts = 0;
+        for block_idx in 0..self.num_of_blocks() {
+            let block = self.read_block_cached(block_idx)?;
+            let mut iter = crate::block::BlockIterator::create_and_seek_to_first(block);
+            while iter.is_valid() {
+                let (_, ts) = split_key_ts(iter.key());
+                max_ts = max_ts.max(ts);
+                iter.next();
+            }
+        }
+        Ok(max_ts)
+    }
 }
 
 #[cfg(test)]
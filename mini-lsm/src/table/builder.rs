@@ -1,15 +1,18 @@
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{Result, Ok};
+use anyhow::{ensure, Result, Ok};
 use bytes::BufMut;
+use crc32c::crc32c;
 
-use super::{BlockMeta, FileObject, SsTable};
+use super::{BlockMeta, BloomFilter, CompressionType, FileObject, SsTable, BLOOM_BITS_PER_KEY, TS_LEN};
 use crate::block::BlockBuilder;
 use crate::lsm_storage::BlockCache;
 
 
-/// Builds an SSTable from key-value pairs.
+/// Builds an SSTable from key-value pairs. Keys are opaque to the builder: callers pass the
+/// already MVCC-encoded `user_key + !ts` bytes (see `crate::table::encode_key_with_ts`), and the
+/// builder stores them unchanged, hashing only the user-key portion for the bloom filter.
 pub struct SsTableBuilder{
     ///
     builder:BlockBuilder,
@@ -17,61 +20,114 @@ pub struct SsTableBuilder{
     data: Vec<u8>,
     pub(super) meta: Vec<BlockMeta>,
     block_size: usize,
+    compression: CompressionType,
+    /// Hash of every key added so far, used to build the table's bloom filter.
+    key_hashes: Vec<u32>,
+    /// Whether readers of the built table should verify block/meta/footer crc32s.
+    verify_checksums: bool,
 }
 
 
 impl SsTableBuilder {
 
-    /// Create a builder based on target block size.    
-    pub fn new(block_size: usize) ->Self {
+    /// Create a builder based on target block size, compressing data blocks
+    /// with `compression` before they are written to the SST. `verify_checksums` is carried
+    /// over to the built `SsTable` and controls whether reads check the crc32s this builder
+    /// always writes.
+    pub fn new(block_size: usize, compression: CompressionType, verify_checksums: bool) ->Self {
         Self{
             builder: BlockBuilder::new(block_size),
             first_key: Vec::new(),
             data: Vec::new(),
             meta: Vec::new(),
             block_size,
+            compression,
+            key_hashes: Vec::new(),
+            verify_checksums,
         }
     }
 
-    pub fn add(&mut self, key: &[u8], value: &[u8]) {
+    /// Add a key-value pair to the table. `key` must already be MVCC-encoded (`user_key + !ts`,
+    /// see `crate::table::encode_key_with_ts`) with more than `TS_LEN` bytes; a shorter key has
+    /// no room for the ts suffix `super::user_key`/`super::split_key_ts` split off and is
+    /// rejected here instead of underflowing inside them.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        ensure!(
+            key.len() > TS_LEN,
+            "key of {} bytes is too short to be ts-encoded (need more than {TS_LEN})",
+            key.len()
+        );
         if self.first_key.is_empty() {
             self.first_key = key.to_vec();
         }
+        self.key_hashes.push(super::hash_key(super::user_key(key)));
 
         if self.builder.add(key, value) {
-            return;
+            return Ok(());
         }
 
         // create a new block builder and append block data
-        self.finish_block();
+        self.finish_block()?;
 
         // add the key-value pair to the next block
         assert!(self.builder.add(key, value));
         self.first_key = key.to_vec();
-
+        Ok(())
     }
 
-    fn finish_block(&mut self) {
+    fn finish_block(&mut self) -> Result<()> {
         let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
 
         let enc_block = builder.build().encode();
-        self.meta.push(BlockMeta { offset: self.data.len(), 
+        let compressed = self.compression.compress(&enc_block)?;
+
+        self.meta.push(BlockMeta { offset: self.data.len(),
             first_key: std::mem::take(&mut self.first_key).into() });
 
-        self.data.extend(enc_block);
+        // each stored block is prefixed with a 1-byte compression tag and the uncompressed
+        // length, then suffixed with a crc32 of everything before it, so `SsTable::read_block`
+        // can decompress and, if enabled, verify it.
+        let mut record = Vec::with_capacity(5 + compressed.len());
+        record.put_u8(self.compression.as_u8());
+        record.put_u32(enc_block.len() as u32);
+        record.extend(compressed);
+        self.data.extend(&record);
+        self.data.put_u32(crc32c(&record));
+        Ok(())
     }
 
-    pub fn build(mut self, id: usize, block_cache: Option<Arc<BlockCache>>, 
+    pub fn build(mut self, id: usize, block_cache: Option<Arc<BlockCache>>,
                     path: impl AsRef<Path>) -> Result<SsTable> {
-        
-        self.finish_block();
+
+        self.finish_block()?;
         let mut buf = self.data;
         let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, &mut buf);
-        buf.put_u32(meta_offset as u32);
+        let mut meta_buf = Vec::new();
+        BlockMeta::encode_block_meta(&self.meta, &mut meta_buf);
+        buf.extend(&meta_buf);
+        buf.put_u32(crc32c(&meta_buf));
+
+        let filter_offset = buf.len();
+        let filter = BloomFilter::build(&self.key_hashes, BLOOM_BITS_PER_KEY);
+        buf.put_slice(&filter.encode());
+
+        let mut footer = Vec::with_capacity(9);
+        footer.put_u32(meta_offset as u32);
+        footer.put_u32(filter_offset as u32);
+        footer.put_u8(self.compression.as_u8());
+        buf.extend(&footer);
+        buf.put_u32(crc32c(&footer));
 
         let file = FileObject::new(path.as_ref(), buf)?;
-        Ok(SsTable { file, id, block_meta_offset: meta_offset, block_metas: self.meta, block_cache})
+        Ok(SsTable {
+            file,
+            id,
+            block_meta_offset: meta_offset,
+            block_metas: self.meta,
+            block_cache,
+            filter,
+            verify_checksums: self.verify_checksums,
+        })
     }
 
     #[cfg(test)]
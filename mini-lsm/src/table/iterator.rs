@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::SsTable;
+use crate::block::BlockIterator;
+use crate::iterators::StorageIterator;
+
+/// Iterates an `SsTable` in key order by walking its data blocks one at a time via
+/// `BlockIterator`, reading each block through `SsTable::read_block_cached` as the iterator
+/// crosses into it. `block_iter` is `None` once the iterator has walked past the table's last
+/// block.
+pub struct SsTableIterator {
+    table: Arc<SsTable>,
+    block_idx: usize,
+    block_iter: Option<BlockIterator>,
+}
+
+impl SsTableIterator {
+    fn block_iter_at(table: &Arc<SsTable>, block_idx: usize) -> Result<Option<BlockIterator>> {
+        if block_idx >= table.num_of_blocks() {
+            return Ok(None);
+        }
+        let block = table.read_block_cached(block_idx)?;
+        Ok(Some(BlockIterator::create_and_seek_to_first(block)))
+    }
+
+    /// Create an iterator over `table`, positioned at its very first entry.
+    pub fn create_and_seek_to_first(table: Arc<SsTable>) -> Result<Self> {
+        let block_iter = Self::block_iter_at(&table, 0)?;
+        Ok(Self {
+            table,
+            block_idx: 0,
+            block_iter,
+        })
+    }
+
+    /// Create an iterator over `table`, positioned at the first entry `>= key`, or exhausted if
+    /// every entry in the table sorts before `key`.
+    pub fn create_and_seek_to_key(table: Arc<SsTable>, key: &[u8]) -> Result<Self> {
+        let mut block_idx = table.find_block_idx(key);
+        let block = table.read_block_cached(block_idx)?;
+        let block_iter = BlockIterator::create_and_seek_to_key(block, key);
+
+        // `find_block_idx` picks the last block whose first key is `<= key`, so `key` may still
+        // sort past every entry in it (e.g. it falls in the gap before the next block's first
+        // key); advance into the next block in that case.
+        let block_iter = if block_iter.is_valid() {
+            Some(block_iter)
+        } else {
+            block_idx += 1;
+            Self::block_iter_at(&table, block_idx)?
+        };
+
+        Ok(Self {
+            table,
+            block_idx,
+            block_iter,
+        })
+    }
+}
+
+impl StorageIterator for SsTableIterator {
+    fn key(&self) -> &[u8] {
+        self.block_iter.as_ref().unwrap().key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.block_iter.as_ref().unwrap().value()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.block_iter.is_some()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        let iter = self.block_iter.as_mut().unwrap();
+        iter.next();
+        if !iter.is_valid() {
+            self.block_idx += 1;
+            self.block_iter = Self::block_iter_at(&self.table, self.block_idx)?;
+        }
+        Ok(())
+    }
+}